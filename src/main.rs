@@ -1,14 +1,177 @@
 use csv::Reader;
+use serde::Serialize;
 use serde_json::Value;
-use std::collections::{HashMap, BTreeMap};
+use std::collections::{HashMap, BTreeMap, BTreeSet};
 use std::error::Error;
 use std::fs::File;
+use std::io::{self, BufRead, BufReader, Cursor, Read};
+use std::time::Instant;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum OpClass {
+    Equality,
+    Range,
+    Membership,
+    Negation,
+}
+
+impl OpClass {
+    fn as_str(&self) -> &'static str {
+        match self {
+            OpClass::Equality => "eq",
+            OpClass::Range => "range",
+            OpClass::Membership => "in",
+            OpClass::Negation => "ne",
+        }
+    }
+}
+
+fn classify_op(op: &str) -> OpClass {
+    match op {
+        "$gt" | "$gte" | "$lt" | "$lte" => OpClass::Range,
+        "$in" | "$nin" | "$regex" => OpClass::Membership,
+        // $ne is a negation, not a point lookup - it's generally the worst
+        // case for index selectivity, so it gets its own bucket instead of
+        // being treated as sargable equality.
+        "$ne" => OpClass::Negation,
+        // $eq, $exists and bare equality all behave like point lookups for indexing
+        _ => OpClass::Equality,
+    }
+}
+
+// A normalized query-shape tree. Mirrors the $and/$or/$nor structure of the
+// original filter so structurally distinct queries don't collapse into the
+// same flat field list.
+#[derive(Debug, Clone, Serialize)]
+enum Shape {
+    And(Vec<Shape>),
+    Or(Vec<Shape>),
+    Leaf { field: String, op: String, class: OpClass },
+}
+
+fn build_shape_from_array(arr: &[Value]) -> Vec<Shape> {
+    arr.iter().map(build_shape).collect()
+}
+
+fn build_shape(obj: &Value) -> Shape {
+    let map = match obj.as_object() {
+        Some(m) => m,
+        None => return Shape::And(Vec::new()),
+    };
+
+    let mut clauses = Vec::new();
+
+    for (key, value) in map.iter() {
+        match key.as_str() {
+            "$and" => {
+                if let Some(arr) = value.as_array() {
+                    clauses.push(Shape::And(build_shape_from_array(arr)));
+                }
+            }
+            "$or" => {
+                if let Some(arr) = value.as_array() {
+                    clauses.push(Shape::Or(build_shape_from_array(arr)));
+                }
+            }
+            "$nor" => {
+                if let Some(arr) = value.as_array() {
+                    // A $nor is a negated Or; keep the Or grouping since the
+                    // index-selection implications (any member field is
+                    // selective) are the same as for $or.
+                    clauses.push(Shape::Or(build_shape_from_array(arr)));
+                }
+            }
+            _ if key.starts_with('$') => {
+                // Unknown top-level operator (e.g. $where) - nothing to key on.
+            }
+            "_id" => {
+                // Every collection already has a default unique index on _id;
+                // skip it the same way extract_fields_from_object does so it
+                // never shows up as an index recommendation.
+            }
+            _ => {
+                if let Some(ops) = value.as_object() {
+                    let has_operators = ops.keys().any(|k| k.starts_with('$'));
+                    if has_operators {
+                        for (op, _) in ops.iter() {
+                            if op.starts_with('$') {
+                                clauses.push(Shape::Leaf {
+                                    field: key.clone(),
+                                    op: op.clone(),
+                                    class: classify_op(op),
+                                });
+                            }
+                        }
+                        continue;
+                    }
+                }
+                // Bare scalar (or plain object without operators) is an equality match.
+                clauses.push(Shape::Leaf {
+                    field: key.clone(),
+                    op: "$eq".to_string(),
+                    class: OpClass::Equality,
+                });
+            }
+        }
+    }
+
+    Shape::And(clauses)
+}
+
+// Canonical, order-stable signature: sibling leaves/clauses are sorted so
+// that `{a:1,b:2}` and `{b:2,a:1}` produce the same key, while `$and`/`$or`
+// nesting is preserved so an `$or` can't be confused with a flat `$and`.
+fn shape_signature(shape: &Shape) -> String {
+    match shape {
+        Shape::Leaf { field, op, class } => format!("{}:{}:{}", field, op, class.as_str()),
+        Shape::And(children) => {
+            let mut parts: Vec<String> = children.iter().map(shape_signature).collect();
+            parts.sort();
+            format!("AND({})", parts.join(","))
+        }
+        Shape::Or(children) => {
+            let mut parts: Vec<String> = children.iter().map(shape_signature).collect();
+            parts.sort();
+            format!("OR({})", parts.join(","))
+        }
+    }
+}
+
+// Flatten a shape into the leaf fields, split by whether they support
+// equality-style seeks (equality and membership, e.g. `$in`) or only
+// range seeks - this is what lets the compound-index suggestion follow
+// the Equality -> Sort -> Range ordering instead of just concatenating lists.
+fn collect_leaf_fields(shape: &Shape, equality_fields: &mut Vec<String>, range_fields: &mut Vec<String>) {
+    match shape {
+        Shape::Leaf { field, class, .. } => {
+            let bucket = match class {
+                OpClass::Range => &mut *range_fields,
+                OpClass::Equality | OpClass::Membership => &mut *equality_fields,
+                // A negation isn't sargable the way equality/membership are,
+                // so it shouldn't be recommended as a leading compound-index field.
+                OpClass::Negation => return,
+            };
+            if !bucket.contains(field) {
+                bucket.push(field.clone());
+            }
+        }
+        Shape::And(children) | Shape::Or(children) => {
+            for child in children {
+                collect_leaf_fields(child, equality_fields, range_fields);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
 struct QueryPattern {
     collection: String,
     operation: String,
     filter_fields: Vec<String>,
+    filter_shape: Shape,
+    equality_fields: Vec<String>,
+    range_fields: Vec<String>,
     sort_fields: Vec<String>,
     index_used: String,
     plan_summary: String,
@@ -133,10 +296,13 @@ fn parse_query_pattern(json_str: &str) -> Option<QueryPattern> {
     let mut collection = String::new();
     let mut operation = String::new();
     let mut filter_fields = Vec::new();
+    let mut equality_fields = Vec::new();
+    let mut range_fields = Vec::new();
     let mut sort_fields = Vec::new();
     let mut plan_summary = "unknown".to_string();
     let mut field_values = HashMap::new();
     let mut duration_ms = None;
+    let mut filter_shape = Shape::And(Vec::new());
     
     // Extract namespace (collection)
     if let Some(ns) = parsed.get("attr")?.get("ns")?.as_str() {
@@ -173,14 +339,16 @@ fn parse_query_pattern(json_str: &str) -> Option<QueryPattern> {
             filter_fields = extract_fields_from_object(filter_obj);
             let filter_values = extract_field_values_from_object(filter_obj, "");
             field_values.extend(filter_values);
+            filter_shape = build_shape(filter_obj);
+            collect_leaf_fields(&filter_shape, &mut equality_fields, &mut range_fields);
         }
-        
+
         // Extract sort fields
         if let Some(sort_obj) = command_obj.get("sort") {
             sort_fields = extract_fields_from_object(sort_obj);
         }
     }
-    
+
     // For getMore, try to get originating command
     if operation == "getMore" {
         if let Some(orig_command) = parsed.get("attr")?.get("originatingCommand") {
@@ -188,6 +356,10 @@ fn parse_query_pattern(json_str: &str) -> Option<QueryPattern> {
                 filter_fields = extract_fields_from_object(filter_obj);
                 let filter_values = extract_field_values_from_object(filter_obj, "");
                 field_values.extend(filter_values);
+                equality_fields.clear();
+                range_fields.clear();
+                filter_shape = build_shape(filter_obj);
+                collect_leaf_fields(&filter_shape, &mut equality_fields, &mut range_fields);
             }
             if let Some(sort_obj) = orig_command.get("sort") {
                 sort_fields = extract_fields_from_object(sort_obj);
@@ -203,6 +375,9 @@ fn parse_query_pattern(json_str: &str) -> Option<QueryPattern> {
         collection,
         operation,
         filter_fields,
+        filter_shape,
+        equality_fields,
+        range_fields,
         sort_fields,
         index_used: "unknown".to_string(),
         plan_summary,
@@ -211,23 +386,81 @@ fn parse_query_pattern(json_str: &str) -> Option<QueryPattern> {
     })
 }
 
-fn find_query_patterns_in_braces(csv_path: &str) -> Result<Vec<(QueryPattern, usize)>, Box<dyn Error>> {
-    let file = File::open(csv_path)?;
+// Canonical key for grouping structurally equivalent queries: the shape
+// signature (see `shape_signature`) distinguishes `$in`/`$or`/range filters
+// that the old Display-based key collapsed into the same bucket.
+fn pattern_shape_key(pattern: &QueryPattern) -> String {
+    format!(
+        "{}|{}|filter:{}|sort:{}|plan:{}",
+        pattern.collection,
+        pattern.operation,
+        shape_signature(&pattern.filter_shape),
+        pattern.sort_fields.join(","),
+        pattern.plan_summary
+    )
+}
+
+// Input is either a legacy CSV export (fields contain JSON recovered by
+// brace-matching) or a `mongod.log` NDJSON stream, where every line is
+// already a standalone JSON log document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InputFormat {
+    Csv,
+    Ndjson,
+}
+
+fn record_pattern(pattern_counts: &mut HashMap<String, (QueryPattern, usize)>, pattern: QueryPattern) {
+    let pattern_key = pattern_shape_key(&pattern);
+
+    match pattern_counts.get_mut(&pattern_key) {
+        Some((existing, count)) => {
+            *count += 1;
+            // Keep the slowest observed duration for the shape so
+            // `--sort duration_ms:desc` surfaces the worst offender, not
+            // whichever sample happened to be read first.
+            if pattern.duration_ms > existing.duration_ms {
+                existing.duration_ms = pattern.duration_ms;
+            }
+        }
+        None => {
+            pattern_counts.insert(pattern_key, (pattern, 1));
+        }
+    }
+}
+
+fn finalize_patterns(
+    pattern_counts: HashMap<String, (QueryPattern, usize)>,
+    empty_message: &str,
+) -> Result<Vec<(QueryPattern, usize)>, Box<dyn Error>> {
+    if pattern_counts.is_empty() {
+        return Err(empty_message.into());
+    }
+
+    // Sort by count in descending order
+    let mut sorted_patterns: Vec<(QueryPattern, usize)> = pattern_counts
+        .into_values()
+        .collect();
+    sorted_patterns.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+
+    Ok(sorted_patterns)
+}
+
+fn find_query_patterns_in_braces(source: impl Read) -> Result<Vec<(QueryPattern, usize)>, Box<dyn Error>> {
     let mut reader = csv::ReaderBuilder::new()
         .flexible(true)
-        .from_reader(file);
-    
+        .from_reader(source);
+
     let mut pattern_counts: HashMap<String, (QueryPattern, usize)> = HashMap::new();
-    
+
     for result in reader.records() {
         let record = result?;
         for field in record.iter() {
             let field_trimmed = field.trim();
-            
+
             // Find all text within curly braces
             let mut brace_depth = 0;
             let mut start_pos = None;
-            
+
             for (i, ch) in field_trimmed.char_indices() {
                 match ch {
                     '{' => {
@@ -241,16 +474,9 @@ fn find_query_patterns_in_braces(csv_path: &str) -> Result<Vec<(QueryPattern, us
                         if brace_depth == 0 {
                             if let Some(start) = start_pos {
                                 let json_content = &field_trimmed[start..=i];
-                                
+
                                 if let Some(pattern) = parse_query_pattern(json_content) {
-                                    let pattern_key = format!("{}", pattern);
-                                    
-                                    match pattern_counts.get_mut(&pattern_key) {
-                                        Some((_, count)) => *count += 1,
-                                        None => {
-                                            pattern_counts.insert(pattern_key, (pattern, 1));
-                                        }
-                                    }
+                                    record_pattern(&mut pattern_counts, pattern);
                                 }
                             }
                             start_pos = None;
@@ -261,18 +487,32 @@ fn find_query_patterns_in_braces(csv_path: &str) -> Result<Vec<(QueryPattern, us
             }
         }
     }
-    
-    if pattern_counts.is_empty() {
-        return Err("No query patterns found within curly braces".into());
+
+    finalize_patterns(pattern_counts, "No query patterns found within curly braces")
+}
+
+// NDJSON input (e.g. a raw `mongod.log`, or one piped straight from `mongod`
+// or a `gunzip` stream) is already one JSON document per line, so it skips
+// the brace-matching pass entirely - that also means Extended-JSON forms
+// like `$date`/`$numberLong` are handled correctly instead of risking a
+// truncated match on an embedded `}` inside a quoted string.
+fn find_query_patterns_in_ndjson(source: impl Read) -> Result<Vec<(QueryPattern, usize)>, Box<dyn Error>> {
+    let reader = BufReader::new(source);
+    let mut pattern_counts: HashMap<String, (QueryPattern, usize)> = HashMap::new();
+
+    for line in reader.lines() {
+        let line = line?;
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+
+        if let Some(pattern) = parse_query_pattern(trimmed) {
+            record_pattern(&mut pattern_counts, pattern);
+        }
     }
-    
-    // Sort by count in descending order
-    let mut sorted_patterns: Vec<(QueryPattern, usize)> = pattern_counts
-        .into_values()
-        .collect();
-    sorted_patterns.sort_by(|a, b| b.1.cmp(&a.1));
-    
-    Ok(sorted_patterns)
+
+    finalize_patterns(pattern_counts, "No query patterns found in NDJSON input")
 }
 
 fn analyze_collection_field_patterns(patterns: &[(QueryPattern, usize)]) -> BTreeMap<String, BTreeMap<String, usize>> {
@@ -283,11 +523,16 @@ fn analyze_collection_field_patterns(patterns: &[(QueryPattern, usize)]) -> BTre
             .entry(pattern.collection.clone())
             .or_insert_with(BTreeMap::new);
         
-        // Count filter fields
-        for field in &pattern.filter_fields {
-            *collection_stats.entry(format!("filter:{}", field)).or_insert(0) += count;
+        // Count filter fields, split by whether they support an equality-style
+        // seek (equality/`$in`) or only a range seek, so the compound-index
+        // suggestion below can follow the Equality -> Sort -> Range ordering.
+        for field in &pattern.equality_fields {
+            *collection_stats.entry(format!("filter_eq:{}", field)).or_insert(0) += count;
         }
-        
+        for field in &pattern.range_fields {
+            *collection_stats.entry(format!("filter_range:{}", field)).or_insert(0) += count;
+        }
+
         // Count sort fields
         for field in &pattern.sort_fields {
             *collection_stats.entry(format!("sort:{}", field)).or_insert(0) += count;
@@ -305,13 +550,13 @@ fn analyze_collection_field_patterns(patterns: &[(QueryPattern, usize)]) -> BTre
     collection_field_counts
 }
 
-fn analyze_field_value_distributions(patterns: &[(QueryPattern, usize)]) -> BTreeMap<String, BTreeMap<String, usize>> {
+fn analyze_field_value_distributions(patterns: &[(QueryPattern, usize)], high_frequency_threshold: usize) -> BTreeMap<String, BTreeMap<String, usize>> {
     let mut field_value_distributions: BTreeMap<String, BTreeMap<String, usize>> = BTreeMap::new();
-    
+
     // Focus on the slowest queries (those with COLLSCAN or high occurrences)
     for (pattern, count) in patterns.iter() {
         // Only analyze patterns that are problematic (COLLSCAN or high frequency)
-        if pattern.plan_summary == "COLLSCAN" || *count > 100 {
+        if pattern.plan_summary == "COLLSCAN" || *count > high_frequency_threshold {
             for (field_name, field_value) in &pattern.field_values {
                 let field_stats = field_value_distributions
                     .entry(format!("{}:{}", pattern.collection, field_name))
@@ -325,11 +570,403 @@ fn analyze_field_value_distributions(patterns: &[(QueryPattern, usize)]) -> BTre
     field_value_distributions
 }
 
+const DEFAULT_DISTRIBUTION_THRESHOLD: usize = 100;
+const DEFAULT_CLUSTER_SIMILARITY_THRESHOLD: f64 = 0.7;
+
+// A family of near-duplicate query shapes collapsed into one representative,
+// so e.g. a trailing optional filter field or a sort tiebreaker doesn't
+// fragment the hot paths into a hundred near-identical lines.
+#[derive(Debug, Clone, Serialize)]
+struct PatternCluster {
+    representative: QueryPattern,
+    total_count: usize,
+    variant_count: usize,
+}
+
+fn collect_leaf_tags(shape: &Shape, tags: &mut BTreeSet<String>) {
+    match shape {
+        Shape::Leaf { field, op, .. } => {
+            tags.insert(format!("{}:{}", field, op));
+        }
+        Shape::And(children) | Shape::Or(children) => {
+            for child in children {
+                collect_leaf_tags(child, tags);
+            }
+        }
+    }
+}
+
+fn operator_tagged_fields(shape: &Shape) -> BTreeSet<String> {
+    let mut tags = BTreeSet::new();
+    collect_leaf_tags(shape, &mut tags);
+    tags
+}
+
+fn jaccard_similarity(a: &BTreeSet<String>, b: &BTreeSet<String>) -> f64 {
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+
+    let intersection = a.intersection(b).count();
+    let union = a.union(b).count();
+
+    intersection as f64 / union as f64
+}
+
+// Two patterns are candidates for the same cluster only if they agree on
+// operation and plan (a COLLSCAN and an IXSCAN over the same fields are not
+// the same problem); the similarity score itself is the Jaccard overlap of
+// their operator-tagged filter-field sets.
+fn pattern_similarity(a: &QueryPattern, b: &QueryPattern) -> f64 {
+    if a.operation != b.operation || a.plan_summary != b.plan_summary {
+        return 0.0;
+    }
+
+    jaccard_similarity(&operator_tagged_fields(&a.filter_shape), &operator_tagged_fields(&b.filter_shape))
+}
+
+// Agglomerates near-duplicate shapes within each collection: patterns are
+// visited most-frequent-first and joined onto the first existing cluster
+// whose representative scores at or above `similarity_threshold`, so the
+// representative is always the most frequent member of its cluster.
+fn cluster_query_patterns(patterns: &[(QueryPattern, usize)], similarity_threshold: f64) -> Vec<PatternCluster> {
+    let mut by_collection: BTreeMap<String, Vec<&(QueryPattern, usize)>> = BTreeMap::new();
+    for entry in patterns {
+        by_collection.entry(entry.0.collection.clone()).or_default().push(entry);
+    }
+
+    let mut clusters = Vec::new();
+
+    for (_collection, mut entries) in by_collection {
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.1));
+
+        let mut collection_clusters: Vec<PatternCluster> = Vec::new();
+        for (pattern, count) in entries {
+            let joined = collection_clusters.iter_mut()
+                .find(|cluster| pattern_similarity(&cluster.representative, pattern) >= similarity_threshold);
+
+            match joined {
+                Some(cluster) => {
+                    cluster.total_count += count;
+                    cluster.variant_count += 1;
+                }
+                None => {
+                    collection_clusters.push(PatternCluster {
+                        representative: pattern.clone(),
+                        total_count: *count,
+                        variant_count: 1,
+                    });
+                }
+            }
+        }
+
+        clusters.extend(collection_clusters);
+    }
+
+    clusters.sort_by_key(|cluster| std::cmp::Reverse(cluster.total_count));
+    clusters
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SortAttribute {
+    Count,
+    DurationMs,
+    Collection,
+    PlanSummary,
+}
+
+impl SortAttribute {
+    fn parse(name: &str) -> Result<SortAttribute, Box<dyn Error>> {
+        match name {
+            "count" => Ok(SortAttribute::Count),
+            "duration_ms" => Ok(SortAttribute::DurationMs),
+            "collection" => Ok(SortAttribute::Collection),
+            "plan_summary" => Ok(SortAttribute::PlanSummary),
+            other => Err(format!(
+                "unknown sort attribute '{}', expected one of: count, duration_ms, collection, plan_summary",
+                other
+            ).into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SortDirection {
+    Asc,
+    Desc,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SortCriterion {
+    attribute: SortAttribute,
+    direction: SortDirection,
+}
+
+// Parses the colon syntax used by `--sort`, e.g. `duration_ms:desc`.
+fn parse_sort_criterion(spec: &str) -> Result<SortCriterion, Box<dyn Error>> {
+    let (attr, dir) = spec.split_once(':')
+        .ok_or_else(|| format!("--sort '{}' must be in the form field:asc|desc", spec))?;
+
+    let direction = match dir {
+        "asc" => SortDirection::Asc,
+        "desc" => SortDirection::Desc,
+        other => return Err(format!("unknown sort direction '{}', expected asc or desc", other).into()),
+    };
+
+    Ok(SortCriterion { attribute: SortAttribute::parse(attr)?, direction })
+}
+
+// Applies the criteria in order as a stable multi-key sort: the first
+// criterion is the primary key, later ones only break ties left by earlier
+// ones - `Vec::sort_by` is stable, so equal-by-all-criteria rows keep their
+// incoming relative order.
+fn sort_patterns(patterns: &mut [(QueryPattern, usize)], criteria: &[SortCriterion]) {
+    patterns.sort_by(|a, b| {
+        for criterion in criteria {
+            let ordering = match criterion.attribute {
+                SortAttribute::Count => a.1.cmp(&b.1),
+                SortAttribute::DurationMs => a.0.duration_ms.cmp(&b.0.duration_ms),
+                SortAttribute::Collection => a.0.collection.cmp(&b.0.collection),
+                SortAttribute::PlanSummary => a.0.plan_summary.cmp(&b.0.plan_summary),
+            };
+            let ordering = match criterion.direction {
+                SortDirection::Asc => ordering,
+                SortDirection::Desc => ordering.reverse(),
+            };
+            if ordering != std::cmp::Ordering::Equal {
+                return ordering;
+            }
+        }
+        std::cmp::Ordering::Equal
+    });
+}
+
+// Shared by the CLI printout and the HTTP JSON response: given one
+// collection's field-usage stats (as produced by
+// `analyze_collection_field_patterns`), pick the Equality -> Sort -> Range
+// ordered field list for the suggested compound index.
+fn suggested_compound_index_for_collection(field_stats: &BTreeMap<String, usize>) -> Vec<String> {
+    let mut sorted_fields: Vec<(&String, &usize)> = field_stats.iter().collect();
+    sorted_fields.sort_by(|a, b| b.1.cmp(a.1));
+
+    let mut eq_fields = Vec::new();
+    let mut range_fields = Vec::new();
+    let mut sort_fields = Vec::new();
+
+    for (field_type, _count) in sorted_fields.iter().take(10) {
+        if let Some(field) = field_type.strip_prefix("filter_eq:") {
+            eq_fields.push(field.to_string());
+        } else if let Some(field) = field_type.strip_prefix("filter_range:") {
+            range_fields.push(field.to_string());
+        } else if let Some(field) = field_type.strip_prefix("sort:") {
+            sort_fields.push(field.to_string());
+        }
+    }
+
+    eq_fields.into_iter().take(2)
+        .chain(sort_fields.into_iter().take(1))
+        .chain(range_fields.into_iter().take(1))
+        .collect()
+}
+
+struct CliArgs {
+    format: InputFormat,
+    input_path: Option<String>,
+    serve_addr: Option<String>,
+    sort_criteria: Vec<SortCriterion>,
+    distribution_threshold: usize,
+    cluster_similarity_threshold: f64,
+}
+
+// Hand-rolled flag parsing (no CLI framework dependency): `--format
+// csv|ndjson`, `--serve <addr>`, repeatable `--sort field:asc|desc`,
+// `--distribution-threshold <n>`, `--cluster-threshold <0.0-1.0>`, plus an
+// optional positional path. With no path, input is read from stdin so logs
+// can be piped straight from `mongod` or a `gunzip` stream.
+fn parse_cli_args() -> Result<CliArgs, Box<dyn Error>> {
+    let mut format = InputFormat::Csv;
+    let mut input_path = None;
+    let mut serve_addr = None;
+    let mut sort_criteria = Vec::new();
+    let mut distribution_threshold = DEFAULT_DISTRIBUTION_THRESHOLD;
+    let mut cluster_similarity_threshold = DEFAULT_CLUSTER_SIMILARITY_THRESHOLD;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => {
+                let value = args.next().ok_or("--format requires a value (csv or ndjson)")?;
+                format = match value.as_str() {
+                    "csv" => InputFormat::Csv,
+                    "ndjson" => InputFormat::Ndjson,
+                    other => return Err(format!("unknown --format '{}', expected csv or ndjson", other).into()),
+                };
+            }
+            "--serve" => {
+                serve_addr = Some(args.next().ok_or("--serve requires an address, e.g. 127.0.0.1:9700")?);
+            }
+            "--sort" => {
+                let value = args.next().ok_or("--sort requires a value, e.g. duration_ms:desc")?;
+                sort_criteria.push(parse_sort_criterion(&value)?);
+            }
+            "--distribution-threshold" => {
+                let value = args.next().ok_or("--distribution-threshold requires a number")?;
+                distribution_threshold = value.parse()
+                    .map_err(|_| format!("--distribution-threshold '{}' is not a number", value))?;
+            }
+            "--cluster-threshold" => {
+                let value = args.next().ok_or("--cluster-threshold requires a number between 0.0 and 1.0")?;
+                cluster_similarity_threshold = value.parse()
+                    .map_err(|_| format!("--cluster-threshold '{}' is not a number", value))?;
+            }
+            other => input_path = Some(other.to_string()),
+        }
+    }
+
+    Ok(CliArgs { format, input_path, serve_addr, sort_criteria, distribution_threshold, cluster_similarity_threshold })
+}
+
+fn open_input(input_path: &Option<String>) -> Result<Box<dyn Read>, Box<dyn Error>> {
+    match input_path {
+        Some(path) => Ok(Box::new(File::open(path)?)),
+        None => Ok(Box::new(io::stdin())),
+    }
+}
+
+const LOGDUMP_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+fn query_param<'a>(url: &'a str, key: &str) -> Option<&'a str> {
+    let (_, query) = url.split_once('?')?;
+    query.split('&').find_map(|kv| {
+        let (k, v) = kv.split_once('=')?;
+        if k == key { Some(v) } else { None }
+    })
+}
+
+// Builds the same analysis the CLI prints, but as a JSON document: top
+// patterns, per-collection field stats, value distributions and a suggested
+// index per collection. `QueryPattern` derives `Serialize` so this is the
+// same struct the CLI renders through `Display`.
+fn build_analysis_json(patterns: &[(QueryPattern, usize)]) -> Value {
+    let collection_analysis = analyze_collection_field_patterns(patterns);
+    let field_distributions = analyze_field_value_distributions(patterns, DEFAULT_DISTRIBUTION_THRESHOLD);
+
+    let top_patterns: Vec<Value> = patterns.iter().take(10)
+        .map(|(pattern, count)| serde_json::json!({ "pattern": pattern, "count": count }))
+        .collect();
+
+    let suggested_indexes: Vec<Value> = collection_analysis.iter()
+        .map(|(collection, field_stats)| {
+            let fields = suggested_compound_index_for_collection(field_stats);
+            serde_json::json!({ "collection": collection, "fields": fields })
+        })
+        .filter(|entry| !entry["fields"].as_array().map(Vec::is_empty).unwrap_or(true))
+        .collect();
+
+    let clusters = cluster_query_patterns(patterns, DEFAULT_CLUSTER_SIMILARITY_THRESHOLD);
+
+    serde_json::json!({
+        "top_patterns": top_patterns,
+        "collection_field_stats": collection_analysis,
+        "field_value_distributions": field_distributions,
+        "suggested_indexes": suggested_indexes,
+        "clusters": clusters,
+    })
+}
+
+fn analyze_body(body: &[u8], format: InputFormat) -> Result<Value, Box<dyn Error>> {
+    let patterns = match format {
+        InputFormat::Csv => find_query_patterns_in_braces(Cursor::new(body)),
+        InputFormat::Ndjson => find_query_patterns_in_ndjson(Cursor::new(body)),
+    }?;
+
+    Ok(build_analysis_json(&patterns))
+}
+
+// Every response goes through here so the version header and request timing
+// are applied uniformly, the way a middleware layer would in a framework
+// with real middleware support.
+fn respond_with_metrics(request: tiny_http::Request, status: u16, body: String, started_at: Instant) {
+    let method = request.method().to_string();
+    let url = request.url().to_string();
+    let elapsed_ms = started_at.elapsed().as_millis();
+
+    let response = tiny_http::Response::from_string(body)
+        .with_status_code(status)
+        .with_header(tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap())
+        .with_header(tiny_http::Header::from_bytes(&b"X-Logdump-Version"[..], LOGDUMP_VERSION.as_bytes()).unwrap());
+
+    println!("{} {} -> {} ({}ms)", method, url, status, elapsed_ms);
+
+    let _ = request.respond(response);
+}
+
+fn handle_connection(mut request: tiny_http::Request) {
+    let started_at = Instant::now();
+
+    if request.method() != &tiny_http::Method::Post || !request.url().starts_with("/analyze") {
+        let body = serde_json::json!({ "error": "POST /analyze?format=ndjson|csv with the log as the request body" }).to_string();
+        respond_with_metrics(request, 404, body, started_at);
+        return;
+    }
+
+    let format = match query_param(request.url(), "format") {
+        Some("csv") => InputFormat::Csv,
+        Some("ndjson") | None => InputFormat::Ndjson,
+        Some(other) => {
+            let body = serde_json::json!({ "error": format!("unknown format '{}', expected csv or ndjson", other) }).to_string();
+            respond_with_metrics(request, 400, body, started_at);
+            return;
+        }
+    };
+
+    let mut body_bytes = Vec::new();
+    if let Err(e) = request.as_reader().read_to_end(&mut body_bytes) {
+        let body = serde_json::json!({ "error": format!("failed to read request body: {}", e) }).to_string();
+        respond_with_metrics(request, 400, body, started_at);
+        return;
+    }
+
+    match analyze_body(&body_bytes, format) {
+        Ok(analysis) => respond_with_metrics(request, 200, analysis.to_string(), started_at),
+        Err(e) => {
+            let body = serde_json::json!({ "error": e.to_string() }).to_string();
+            respond_with_metrics(request, 422, body, started_at);
+        }
+    }
+}
+
+fn run_server(addr: &str) -> Result<(), Box<dyn Error>> {
+    let server = tiny_http::Server::http(addr).map_err(|e| format!("failed to bind {}: {}", addr, e))?;
+    println!("logdump-parse v{} listening on http://{}", LOGDUMP_VERSION, addr);
+
+    for request in server.incoming_requests() {
+        handle_connection(request);
+    }
+
+    Ok(())
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
-    let csv_file = "/Users/rahulhegde/Downloads/Untitled Discover session (5).csv";
-    
-    match find_query_patterns_in_braces(csv_file) {
-        Ok(patterns) => {
+    let cli = parse_cli_args()?;
+
+    if let Some(addr) = &cli.serve_addr {
+        return run_server(addr);
+    }
+
+    let input = open_input(&cli.input_path)?;
+
+    let analysis = match cli.format {
+        InputFormat::Csv => find_query_patterns_in_braces(input),
+        InputFormat::Ndjson => find_query_patterns_in_ndjson(input),
+    };
+
+    match analysis {
+        Ok(mut patterns) => {
+            if !cli.sort_criteria.is_empty() {
+                sort_patterns(&mut patterns, &cli.sort_criteria);
+            }
+
             println!("MongoDB Slow Query Analysis");
             println!("{}", "=".repeat(100));
             
@@ -348,9 +985,21 @@ fn main() -> Result<(), Box<dyn Error>> {
                 }
             }
             
+            // Clustered query shapes - collapses near-duplicate patterns (e.g. a
+            // trailing optional filter field, or a sort tiebreaker) within a
+            // collection so one recommendation covers the whole family.
+            let clusters = cluster_query_patterns(&patterns, cli.cluster_similarity_threshold);
+            println!("\n🧩 CLUSTERED QUERY SHAPES (similarity >= {:.2}):", cli.cluster_similarity_threshold);
+            println!("{}", "-".repeat(80));
+            for (i, cluster) in clusters.iter().take(10).enumerate() {
+                println!("{}. {} (total {} occurrences across {} variant{})",
+                         i + 1, cluster.representative, cluster.total_count, cluster.variant_count,
+                         if cluster.variant_count == 1 { "" } else { "s" });
+            }
+
             // Collection-specific analysis
             let collection_analysis = analyze_collection_field_patterns(&patterns);
-            let field_distributions = analyze_field_value_distributions(&patterns);
+            let field_distributions = analyze_field_value_distributions(&patterns, cli.distribution_threshold);
             
             println!("\nüîç COLLECTION-SPECIFIC FIELD ANALYSIS:");
             println!("{}", "=".repeat(100));
@@ -365,49 +1014,50 @@ fn main() -> Result<(), Box<dyn Error>> {
                 
                 // Show top problematic patterns for this collection
                 let mut collscan_count = 0;
-                let mut most_used_filters = Vec::new();
+                let mut most_used_eq = Vec::new();
+                let mut most_used_range = Vec::new();
                 let mut most_used_sorts = Vec::new();
-                
+
                 for (field_type, count) in sorted_fields.iter().take(10) {
                     if field_type.starts_with("plan:COLLSCAN") {
                         collscan_count = **count;
-                    } else if field_type.starts_with("filter:") {
-                        most_used_filters.push((field_type.strip_prefix("filter:").unwrap(), **count));
+                    } else if field_type.starts_with("filter_eq:") {
+                        most_used_eq.push((field_type.strip_prefix("filter_eq:").unwrap(), **count));
+                    } else if field_type.starts_with("filter_range:") {
+                        most_used_range.push((field_type.strip_prefix("filter_range:").unwrap(), **count));
                     } else if field_type.starts_with("sort:") {
                         most_used_sorts.push((field_type.strip_prefix("sort:").unwrap(), **count));
                     }
-                    
-                    println!("  ‚Ä¢ {} ‚Üí {} occurrences", field_type, count);
+
+                    println!("  • {} → {} occurrences", field_type, count);
                 }
-                
+
                 // Provide specific recommendations
                 if collscan_count > 0 {
-                    println!("  ‚ö†Ô∏è  {} COLLECTION SCANS detected!", collscan_count);
-                    
-                    if !most_used_filters.is_empty() {
-                        let top_filter_fields: Vec<&str> = most_used_filters.iter().take(3).map(|(f, _)| *f).collect();
-                        println!("  üí° URGENT: Add index on frequently filtered fields: [{}]", top_filter_fields.join(", "));
+                    println!("  ⚠️  {} COLLECTION SCANS detected!", collscan_count);
+
+                    if !most_used_eq.is_empty() || !most_used_range.is_empty() {
+                        let top_filter_fields: Vec<&str> = most_used_eq.iter().chain(most_used_range.iter())
+                            .take(3).map(|(f, _)| *f).collect();
+                        println!("  💡 URGENT: Add index on frequently filtered fields: [{}]", top_filter_fields.join(", "));
                     }
-                    
+
                     if !most_used_sorts.is_empty() {
                         let top_sort_fields: Vec<&str> = most_used_sorts.iter().take(2).map(|(f, _)| *f).collect();
-                        println!("  üí° Consider compound index including sort fields: [{}]", top_sort_fields.join(", "));
+                        println!("  💡 Consider compound index including sort fields: [{}]", top_sort_fields.join(", "));
                     }
                 }
-                
-                // Show suggested compound indexes
-                if !most_used_filters.is_empty() && !most_used_sorts.is_empty() {
-                    let suggested_compound: Vec<String> = most_used_filters.iter().take(2)
-                        .map(|(f, _)| f.to_string())
-                        .chain(most_used_sorts.iter().take(1).map(|(f, _)| f.to_string()))
-                        .collect();
-                    
-                    println!("  üéØ Suggested compound index: db.{}.createIndex({{ {} }})", 
-                             collection, 
+
+                // Show the suggested compound index, following the Equality -> Sort ->
+                // Range ordering so range-bound fields don't outrank the sort key.
+                let suggested_compound = suggested_compound_index_for_collection(field_stats);
+                if !suggested_compound.is_empty() {
+                    println!("  🎯 Suggested compound index: db.{}.createIndex({{ {} }})",
+                             collection,
                              suggested_compound.iter().map(|f| format!("{}: 1", f)).collect::<Vec<_>>().join(", "));
                 }
             }
-            
+
             // Field value distribution analysis
             if !field_distributions.is_empty() {
                 println!("\nüìà FIELD VALUE DISTRIBUTION ANALYSIS (Slowest Queries):");
@@ -450,6 +1100,287 @@ fn main() -> Result<(), Box<dyn Error>> {
             eprintln!("Error: {}", e);
         }
     }
-    
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shape_of(filter_json: &str) -> Shape {
+        build_shape(&serde_json::from_str(filter_json).unwrap())
+    }
+
+    #[test]
+    fn build_shape_classifies_comparison_operators() {
+        let shape = shape_of(r#"{"age": {"$gte": 18, "$lt": 65}, "status": {"$in": ["a", "b"]}}"#);
+        let mut equality_fields = Vec::new();
+        let mut range_fields = Vec::new();
+        collect_leaf_fields(&shape, &mut equality_fields, &mut range_fields);
+
+        assert_eq!(equality_fields, vec!["status".to_string()]);
+        assert_eq!(range_fields, vec!["age".to_string()]);
+    }
+
+    #[test]
+    fn build_shape_treats_bare_scalar_as_equality() {
+        let shape = shape_of(r#"{"region": "us"}"#);
+        assert_eq!(shape_signature(&shape), "AND(region:$eq:eq)");
+    }
+
+    #[test]
+    fn build_shape_skips_id_field() {
+        let shape = shape_of(r#"{"_id": "abc123", "status": "pending"}"#);
+        let mut equality_fields = Vec::new();
+        let mut range_fields = Vec::new();
+        collect_leaf_fields(&shape, &mut equality_fields, &mut range_fields);
+
+        assert_eq!(equality_fields, vec!["status".to_string()]);
+        assert!(range_fields.is_empty());
+    }
+
+    #[test]
+    fn build_shape_excludes_ne_from_both_field_buckets() {
+        let shape = shape_of(r#"{"status": {"$ne": "closed"}}"#);
+        let mut equality_fields = Vec::new();
+        let mut range_fields = Vec::new();
+        collect_leaf_fields(&shape, &mut equality_fields, &mut range_fields);
+
+        assert!(equality_fields.is_empty());
+        assert!(range_fields.is_empty());
+    }
+
+    #[test]
+    fn shape_signature_is_order_stable_for_and() {
+        let a = shape_of(r#"{"a": 1, "b": 2}"#);
+        let b = shape_of(r#"{"b": 2, "a": 1}"#);
+        assert_eq!(shape_signature(&a), shape_signature(&b));
+    }
+
+    #[test]
+    fn shape_signature_distinguishes_or_from_and() {
+        let and_shape = shape_of(r#"{"status": "a", "region": "us"}"#);
+        let or_shape = shape_of(r#"{"$or": [{"status": "a"}, {"region": "us"}]}"#);
+        assert_ne!(shape_signature(&and_shape), shape_signature(&or_shape));
+    }
+
+    #[test]
+    fn pattern_shape_key_distinguishes_plan_summary() {
+        let collscan = r#"{"attr":{"ns":"db.orders","planSummary":"COLLSCAN","durationMillis":10,"command":{"find":"orders","filter":{"status":"a"}}}}"#;
+        let ixscan = r#"{"attr":{"ns":"db.orders","planSummary":"IXSCAN { status: 1 }","durationMillis":10,"command":{"find":"orders","filter":{"status":"a"}}}}"#;
+
+        let a = parse_query_pattern(collscan).unwrap();
+        let b = parse_query_pattern(ixscan).unwrap();
+
+        assert_ne!(pattern_shape_key(&a), pattern_shape_key(&b));
+    }
+
+    #[test]
+    fn record_pattern_keeps_the_slowest_duration_for_a_shape() {
+        let mut pattern_counts = HashMap::new();
+        let fast = parse_query_pattern(
+            r#"{"attr":{"ns":"db.orders","planSummary":"COLLSCAN","durationMillis":2,"command":{"find":"orders","filter":{"status":"open"}}}}"#,
+        ).unwrap();
+        let slow = parse_query_pattern(
+            r#"{"attr":{"ns":"db.orders","planSummary":"COLLSCAN","durationMillis":9000,"command":{"find":"orders","filter":{"status":"open"}}}}"#,
+        ).unwrap();
+
+        record_pattern(&mut pattern_counts, fast);
+        record_pattern(&mut pattern_counts, slow);
+
+        let (merged, count) = pattern_counts.into_values().next().unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(merged.duration_ms, Some(9000));
+    }
+
+    #[test]
+    fn parse_sort_criterion_parses_known_attributes() {
+        let criterion = parse_sort_criterion("duration_ms:desc").unwrap();
+        assert_eq!(criterion.attribute, SortAttribute::DurationMs);
+        assert_eq!(criterion.direction, SortDirection::Desc);
+    }
+
+    #[test]
+    fn parse_sort_criterion_rejects_unknown_attribute() {
+        assert!(parse_sort_criterion("bogus:asc").is_err());
+    }
+
+    #[test]
+    fn parse_sort_criterion_rejects_missing_direction() {
+        assert!(parse_sort_criterion("count").is_err());
+    }
+
+    #[test]
+    fn sort_patterns_ranks_by_duration_after_merging_occurrences() {
+        let mut pattern_counts = HashMap::new();
+        let shape_a_fast = parse_query_pattern(
+            r#"{"attr":{"ns":"db.a","planSummary":"COLLSCAN","durationMillis":2,"command":{"find":"a","filter":{"status":"open"}}}}"#,
+        ).unwrap();
+        let shape_a_slow = parse_query_pattern(
+            r#"{"attr":{"ns":"db.a","planSummary":"COLLSCAN","durationMillis":9000,"command":{"find":"a","filter":{"status":"open"}}}}"#,
+        ).unwrap();
+        let shape_b = parse_query_pattern(
+            r#"{"attr":{"ns":"db.b","planSummary":"COLLSCAN","durationMillis":100,"command":{"find":"b","filter":{"status":"open"}}}}"#,
+        ).unwrap();
+
+        record_pattern(&mut pattern_counts, shape_a_fast);
+        record_pattern(&mut pattern_counts, shape_a_slow);
+        record_pattern(&mut pattern_counts, shape_b);
+
+        let mut patterns = finalize_patterns(pattern_counts, "no patterns").unwrap();
+        sort_patterns(&mut patterns, &[
+            SortCriterion { attribute: SortAttribute::DurationMs, direction: SortDirection::Desc },
+        ]);
+
+        assert_eq!(patterns[0].0.collection, "a");
+        assert_eq!(patterns[0].0.duration_ms, Some(9000));
+    }
+
+    #[test]
+    fn sort_patterns_applies_multi_key_stable_sort() {
+        let a = parse_query_pattern(
+            r#"{"attr":{"ns":"db.a","planSummary":"COLLSCAN","durationMillis":10,"command":{"find":"a","filter":{}}}}"#,
+        ).unwrap();
+        let b = parse_query_pattern(
+            r#"{"attr":{"ns":"db.b","planSummary":"COLLSCAN","durationMillis":10,"command":{"find":"b","filter":{}}}}"#,
+        ).unwrap();
+        let c = parse_query_pattern(
+            r#"{"attr":{"ns":"db.a","planSummary":"COLLSCAN","durationMillis":10,"command":{"find":"a","filter":{}}}}"#,
+        ).unwrap();
+
+        let mut patterns = vec![(a, 1usize), (b, 2usize), (c, 1usize)];
+        sort_patterns(&mut patterns, &[
+            SortCriterion { attribute: SortAttribute::Collection, direction: SortDirection::Asc },
+            SortCriterion { attribute: SortAttribute::Count, direction: SortDirection::Desc },
+        ]);
+
+        let collections: Vec<&str> = patterns.iter().map(|(p, _)| p.collection.as_str()).collect();
+        assert_eq!(collections, vec!["a", "a", "b"]);
+    }
+
+    #[test]
+    fn jaccard_similarity_of_identical_sets_is_one() {
+        let a: BTreeSet<String> = ["status:$eq", "region:$eq"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(jaccard_similarity(&a, &a), 1.0);
+    }
+
+    #[test]
+    fn jaccard_similarity_of_disjoint_sets_is_zero() {
+        let a: BTreeSet<String> = ["status:$eq"].iter().map(|s| s.to_string()).collect();
+        let b: BTreeSet<String> = ["region:$eq"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(jaccard_similarity(&a, &b), 0.0);
+    }
+
+    #[test]
+    fn cluster_query_patterns_merges_near_duplicates_above_threshold() {
+        let base = parse_query_pattern(
+            r#"{"attr":{"ns":"db.orders","planSummary":"COLLSCAN","durationMillis":10,"command":{"find":"orders","filter":{"status":"open"}}}}"#,
+        ).unwrap();
+        let near_duplicate = parse_query_pattern(
+            r#"{"attr":{"ns":"db.orders","planSummary":"COLLSCAN","durationMillis":10,"command":{"find":"orders","filter":{"status":"open","region":"us"}}}}"#,
+        ).unwrap();
+
+        let patterns = vec![(base, 5usize), (near_duplicate, 3usize)];
+        let clusters = cluster_query_patterns(&patterns, 0.4);
+
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(clusters[0].total_count, 8);
+        assert_eq!(clusters[0].variant_count, 2);
+    }
+
+    #[test]
+    fn cluster_query_patterns_keeps_different_plans_separate() {
+        let collscan = parse_query_pattern(
+            r#"{"attr":{"ns":"db.orders","planSummary":"COLLSCAN","durationMillis":10,"command":{"find":"orders","filter":{"status":"open"}}}}"#,
+        ).unwrap();
+        let ixscan = parse_query_pattern(
+            r#"{"attr":{"ns":"db.orders","planSummary":"IXSCAN","durationMillis":10,"command":{"find":"orders","filter":{"status":"open"}}}}"#,
+        ).unwrap();
+
+        let patterns = vec![(collscan, 5usize), (ixscan, 5usize)];
+        let clusters = cluster_query_patterns(&patterns, 0.9);
+
+        assert_eq!(clusters.len(), 2);
+    }
+
+    #[test]
+    fn find_query_patterns_in_ndjson_parses_one_document_per_line() {
+        let input = concat!(
+            r#"{"attr":{"ns":"db.orders","planSummary":"COLLSCAN","durationMillis":5,"command":{"find":"orders","filter":{"status":"open"}}}}"#, "\n",
+            "\n",
+            r#"{"attr":{"ns":"db.orders","planSummary":"COLLSCAN","durationMillis":7,"command":{"find":"orders","filter":{"status":"open"}}}}"#, "\n",
+        );
+
+        let patterns = find_query_patterns_in_ndjson(Cursor::new(input.as_bytes())).unwrap();
+
+        assert_eq!(patterns.len(), 1);
+        assert_eq!(patterns[0].1, 2);
+    }
+
+    #[test]
+    fn find_query_patterns_in_ndjson_errors_when_nothing_parses() {
+        let result = find_query_patterns_in_ndjson(Cursor::new(b"not json\n".as_slice()));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn find_query_patterns_in_braces_extracts_json_from_csv_field() {
+        let line = r#"{"attr":{"ns":"db.orders","planSummary":"COLLSCAN","durationMillis":5,"command":{"find":"orders","filter":{"status":"open"}}}}"#;
+        // CSV-quote the field and double the embedded quotes, per RFC 4180.
+        let escaped = line.replace('"', "\"\"");
+        let csv_input = format!("message\n\"log line with {} embedded\"\n", escaped);
+
+        let patterns = find_query_patterns_in_braces(Cursor::new(csv_input.into_bytes())).unwrap();
+
+        assert_eq!(patterns.len(), 1);
+        assert_eq!(patterns[0].0.collection, "orders");
+    }
+
+    #[test]
+    fn query_param_finds_the_requested_key() {
+        assert_eq!(query_param("/analyze?format=ndjson&sort=count:desc", "format"), Some("ndjson"));
+        assert_eq!(query_param("/analyze?format=ndjson&sort=count:desc", "sort"), Some("count:desc"));
+    }
+
+    #[test]
+    fn query_param_returns_none_when_key_or_query_is_missing() {
+        assert_eq!(query_param("/analyze?format=ndjson", "missing"), None);
+        assert_eq!(query_param("/analyze", "format"), None);
+    }
+
+    #[test]
+    fn analyze_body_rejects_unparseable_ndjson_body() {
+        let result = analyze_body(b"not json", InputFormat::Ndjson);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn analyze_body_returns_the_same_json_shape_for_csv_and_ndjson() {
+        let line = r#"{"attr":{"ns":"db.orders","planSummary":"COLLSCAN","durationMillis":5,"command":{"find":"orders","filter":{"status":"open"}}}}"#;
+
+        let ndjson_result = analyze_body(line.as_bytes(), InputFormat::Ndjson).unwrap();
+
+        let escaped = line.replace('"', "\"\"");
+        let csv_input = format!("message\n\"log line with {} embedded\"\n", escaped);
+        let csv_result = analyze_body(csv_input.as_bytes(), InputFormat::Csv).unwrap();
+
+        for result in [&ndjson_result, &csv_result] {
+            assert_eq!(result["top_patterns"].as_array().unwrap().len(), 1);
+            assert_eq!(result["top_patterns"][0]["pattern"]["collection"], "orders");
+            assert!(result["collection_field_stats"].is_object());
+            assert!(result["clusters"].is_array());
+        }
+    }
+
+    #[test]
+    fn build_analysis_json_omits_suggested_index_for_collections_with_no_filter_fields() {
+        let pattern = parse_query_pattern(
+            r#"{"attr":{"ns":"db.orders","planSummary":"COLLSCAN","durationMillis":5,"command":{"find":"orders"}}}"#,
+        ).unwrap();
+
+        let analysis = build_analysis_json(&[(pattern, 1usize)]);
+
+        assert!(analysis["suggested_indexes"].as_array().unwrap().is_empty());
+    }
+}